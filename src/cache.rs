@@ -0,0 +1,146 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::RepoStatus;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    status: RepoStatus,
+}
+
+/// Build a cache key from the repo's working directory, the index file's
+/// mtime, and the current HEAD OID, alongside the working directory
+/// itself (used to namespace the on-disk cache file per repo). Any of the
+/// three key components changing means the cached status is stale.
+pub fn cache_key(repo: &git2::Repository) -> Option<(String, String)> {
+    let workdir = repo.workdir()?.to_string_lossy().to_string();
+    let index_mtime = fs::metadata(repo.path().join("index"))
+        .and_then(|m| m.modified())
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    let head = repo.head().ok()?.target()?;
+
+    let key = format!("{}:{}:{}", workdir, index_mtime, head);
+    Some((workdir, key))
+}
+
+/// Cache file path for `workdir`, namespaced by a hash of the workdir
+/// itself so distinct repos (e.g. two shells in two different large
+/// repos) don't overwrite each other's cached status in a single
+/// last-writer-wins file.
+fn cache_path(workdir: &str) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    workdir.hash(&mut hasher);
+
+    dirs::cache_dir().map(|d| {
+        d.join("rustygitprompt")
+            .join(format!("status-{:x}.json", hasher.finish()))
+    })
+}
+
+/// Load the cached status if present and still fresh for `key`.
+pub fn load(workdir: &str, key: &str) -> Option<RepoStatus> {
+    let path = cache_path(workdir)?;
+    let contents = fs::read_to_string(path).ok()?;
+    let entry: CacheEntry = serde_json::from_str(&contents).ok()?;
+
+    if entry.key == key {
+        Some(entry.status)
+    } else {
+        None
+    }
+}
+
+/// Persist `status` under `key`, overwriting whatever was cached before
+/// for this workdir. Best-effort: a write failure (e.g. read-only cache
+/// dir) is silently ignored since the caller already has a correct status
+/// in hand.
+pub fn store(workdir: &str, key: &str, status: &RepoStatus) {
+    let Some(path) = cache_path(workdir) else { return };
+
+    if let Some(parent) = path.parent() {
+        if fs::create_dir_all(parent).is_err() {
+            return;
+        }
+    }
+
+    let entry = CacheEntry {
+        key: key.to_string(),
+        status: status.clone(),
+    };
+
+    if let Ok(json) = serde_json::to_string(&entry) {
+        let _ = fs::write(path, json);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_repo(name: &str) -> (git2::Repository, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rgp-cache-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        (git2::Repository::init(&dir).unwrap(), dir)
+    }
+
+    fn commit(repo: &git2::Repository, message: &str) {
+        let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+        let mut index = repo.index().unwrap();
+        let tree_id = index.write_tree().unwrap();
+        // `cache_key` reads the index file's mtime, so it must exist on disk.
+        index.write().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .unwrap();
+    }
+
+    #[test]
+    fn cache_key_is_none_before_the_first_commit() {
+        let (repo, dir) = temp_repo("no-commits");
+        assert!(cache_key(&repo).is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn cache_key_changes_when_head_moves() {
+        let (repo, dir) = temp_repo("head-moves");
+        commit(&repo, "first");
+        let (workdir, key1) = cache_key(&repo).expect("key after first commit");
+        assert!(workdir.contains("rgp-cache-test"));
+
+        commit(&repo, "second");
+        let (_, key2) = cache_key(&repo).expect("key after second commit");
+
+        assert_ne!(key1, key2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn store_then_load_roundtrips_and_rejects_a_stale_key() {
+        let (repo, dir) = temp_repo("roundtrip");
+        commit(&repo, "first");
+        let (workdir, key) = cache_key(&repo).unwrap();
+
+        let mut status = RepoStatus::default();
+        status.untracked = Some(3);
+        store(&workdir, &key, &status);
+
+        let loaded = load(&workdir, &key).expect("fresh key should hit");
+        assert_eq!(loaded.untracked, Some(3));
+        assert!(load(&workdir, "stale-key").is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use colored::{Color, Colorize};
+use serde::Deserialize;
+
+/// The status components a format template can reference, each written as
+/// `$name` (e.g. `$conflicted`, `$staged`, `$untracked`).
+pub const VARIABLES: &[&str] = &[
+    "new",
+    "staged",
+    "renamed_staged",
+    "deleted_staged",
+    "modified",
+    "renamed",
+    "deleted",
+    "untracked",
+    "conflicted",
+    "stashed",
+    "ahead",
+    "behind",
+    "diverged",
+    "uptodate",
+    "no_upstream",
+];
+
+/// How the prompt is rendered: the default ANSI-colored line, or a single
+/// JSON object for tools (e.g. shell plugins) that want structured data
+/// instead of parsing escape codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+/// Symbol and color for a single status component.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Symbol {
+    pub symbol: String,
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+impl Symbol {
+    fn new(symbol: &str, color: &str) -> Self {
+        Symbol {
+            symbol: symbol.to_string(),
+            color: Some(color.to_string()),
+        }
+    }
+
+    pub fn color(&self) -> Option<Color> {
+        self.color.as_deref().and_then(parse_color)
+    }
+}
+
+/// User-facing configuration: a format template plus the symbol/color used
+/// for each variable the template may reference.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub format: String,
+    pub symbols: HashMap<String, Symbol>,
+    /// Color for the branch name itself, e.g. via `RGP_BRANCH_COLOR`.
+    pub branch_color: Option<Color>,
+    /// Index entry count above which `status` switches from the exact
+    /// full workdir scan to the cached, index-vs-HEAD fast path.
+    pub fast_status_threshold: usize,
+    /// Selected via `--format json` or `RGP_FORMAT=json`; never read from
+    /// the config file since it's a per-invocation choice, not a preference.
+    pub output_format: OutputFormat,
+    /// Explicit override for the local-divergence comparison branch, e.g.
+    /// via `RGP_LOCAL_BASE=develop`. When unset, it's auto-detected from
+    /// `init.defaultBranch` and a `main`/`master`/`trunk` probe.
+    pub local_base: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            format: default_format(),
+            symbols: default_symbols(),
+            branch_color: None,
+            fast_status_threshold: default_fast_status_threshold(),
+            output_format: OutputFormat::default(),
+            local_base: None,
+        }
+    }
+}
+
+/// Mirrors the subset of [`Config`] that's actually read from the TOML
+/// file, with every field optional so a config that only sets e.g.
+/// `[symbols.conflicted]` doesn't make serde treat the rest of the
+/// `symbols` table (or any other field) as absent. [`RawConfig::into_config`]
+/// then overlays whatever was present onto the built-in defaults, instead
+/// of serde's whole-field `#[serde(default = ...)]` replacing the entire
+/// map the moment the user writes a partial override.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct RawConfig {
+    format: Option<String>,
+    symbols: Option<HashMap<String, Symbol>>,
+    fast_status_threshold: Option<usize>,
+    local_base: Option<String>,
+}
+
+impl RawConfig {
+    fn into_config(self) -> Config {
+        let mut symbols = default_symbols();
+        if let Some(overrides) = self.symbols {
+            symbols.extend(overrides);
+        }
+
+        Config {
+            format: self.format.unwrap_or_else(default_format),
+            symbols,
+            branch_color: None,
+            fast_status_threshold: self
+                .fast_status_threshold
+                .unwrap_or_else(default_fast_status_threshold),
+            output_format: OutputFormat::default(),
+            local_base: self.local_base,
+        }
+    }
+}
+
+fn default_fast_status_threshold() -> usize {
+    2000
+}
+
+fn default_format() -> String {
+    "$diverged$behind$ahead$uptodate$no_upstream$conflicted$stashed$new$staged$renamed_staged$deleted_staged$modified$renamed$deleted$untracked".to_string()
+}
+
+fn default_symbols() -> HashMap<String, Symbol> {
+    let mut symbols = HashMap::new();
+    symbols.insert("new".to_string(), Symbol::new("N", "green"));
+    symbols.insert("staged".to_string(), Symbol::new("M", "green"));
+    symbols.insert("renamed_staged".to_string(), Symbol::new("R", "green"));
+    symbols.insert("deleted_staged".to_string(), Symbol::new("D", "green"));
+    symbols.insert("modified".to_string(), Symbol::new("M", "red"));
+    symbols.insert("renamed".to_string(), Symbol::new("R", "red"));
+    symbols.insert("deleted".to_string(), Symbol::new("D", "red"));
+    symbols.insert("untracked".to_string(), Symbol::new("U", "blue"));
+    symbols.insert("conflicted".to_string(), Symbol::new("=", "red"));
+    symbols.insert("stashed".to_string(), Symbol::new("$", "yellow"));
+    symbols.insert("ahead".to_string(), Symbol::new("↑", "green"));
+    symbols.insert("behind".to_string(), Symbol::new("↓", "red"));
+    symbols.insert("diverged".to_string(), Symbol::new("⇵", "yellow"));
+    symbols.insert("uptodate".to_string(), Symbol::new("≡", ""));
+    symbols.insert("no_upstream".to_string(), Symbol::new("⚡", "red"));
+    symbols.insert("local_ahead".to_string(), Symbol::new("←", "magenta"));
+    symbols.insert("local_behind".to_string(), Symbol::new("→", "magenta"));
+    symbols.insert("local_diverged".to_string(), Symbol::new("↔", "magenta"));
+    symbols.insert("local_none".to_string(), Symbol::new("⦰", "red"));
+    symbols
+}
+
+impl Config {
+    /// Load the config from `RGP_CONFIG` if set, else from
+    /// `~/.config/rustygitprompt.toml`, and fall back to built-in defaults
+    /// when the chosen file is missing or doesn't parse (a set `RGP_CONFIG`
+    /// that is missing does not fall through to the default path).
+    pub fn load() -> Self {
+        let path = env::var("RGP_CONFIG")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(default_config_path);
+
+        let raw: RawConfig = path
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default();
+
+        let mut cfg = raw.into_config();
+
+        cfg.apply_env_colors();
+
+        if let Some(threshold) = env::var("RGP_FAST_STATUS_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            cfg.fast_status_threshold = threshold;
+        }
+
+        cfg.output_format = output_format();
+
+        if let Ok(base) = env::var("RGP_LOCAL_BASE") {
+            cfg.local_base = Some(base);
+        }
+
+        cfg
+    }
+
+    /// Override symbol colors (and the branch color) from `RGP_<NAME>_COLOR`
+    /// environment variables, e.g. `RGP_BRANCH_COLOR`, `RGP_AHEAD_COLOR`,
+    /// `RGP_MODIFIED_COLOR`. Unset or unparsable variables leave the
+    /// existing (config file or built-in default) color untouched.
+    fn apply_env_colors(&mut self) {
+        if let Some(color) = env::var("RGP_BRANCH_COLOR").ok().and_then(|v| parse_color(&v)) {
+            self.branch_color = Some(color);
+        }
+
+        for (name, symbol) in self.symbols.iter_mut() {
+            let var_name = format!("RGP_{}_COLOR", name.to_ascii_uppercase());
+            if let Ok(value) = env::var(&var_name) {
+                if parse_color(&value).is_some() {
+                    symbol.color = Some(value);
+                }
+            }
+        }
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+}
+
+/// Resolve the output format from `--format <name>`/`--format=<name>` on
+/// the command line, falling back to `RGP_FORMAT`, and finally to
+/// [`OutputFormat::Human`]. The CLI flag wins so a one-off invocation can
+/// override whatever the shell config exports.
+fn output_format() -> OutputFormat {
+    let args: Vec<String> = env::args().collect();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        let value = if arg == "--format" {
+            iter.next().map(String::as_str)
+        } else {
+            arg.strip_prefix("--format=")
+        };
+
+        if let Some(name) = value {
+            if let Some(format) = parse_output_format(name) {
+                return format;
+            }
+        }
+    }
+
+    env::var("RGP_FORMAT")
+        .ok()
+        .and_then(|v| parse_output_format(&v))
+        .unwrap_or_default()
+}
+
+fn parse_output_format(name: &str) -> Option<OutputFormat> {
+    match name.to_ascii_lowercase().as_str() {
+        "json" => Some(OutputFormat::Json),
+        "human" => Some(OutputFormat::Human),
+        _ => None,
+    }
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("rustygitprompt.toml"))
+}
+
+fn parse_color(name: &str) -> Option<Color> {
+    match name.to_ascii_lowercase().as_str() {
+        "black" => Some(Color::Black),
+        "red" => Some(Color::Red),
+        "green" => Some(Color::Green),
+        "yellow" => Some(Color::Yellow),
+        "blue" => Some(Color::Blue),
+        "magenta" => Some(Color::Magenta),
+        "cyan" => Some(Color::Cyan),
+        "white" => Some(Color::White),
+        "bright black" => Some(Color::BrightBlack),
+        "bright red" => Some(Color::BrightRed),
+        "bright green" => Some(Color::BrightGreen),
+        "bright yellow" => Some(Color::BrightYellow),
+        "bright blue" => Some(Color::BrightBlue),
+        "bright magenta" => Some(Color::BrightMagenta),
+        "bright cyan" => Some(Color::BrightCyan),
+        "bright white" => Some(Color::BrightWhite),
+        _ => None,
+    }
+}
+
+/// Render `count` followed by the symbol configured for `var`, uncolored
+/// when the symbol has no (or an unknown) color. Renders nothing when no
+/// symbol is configured for `var`.
+pub fn render_var(config: &Config, var: &str, count: usize) -> String {
+    match config.symbol(var) {
+        Some(sym) => match sym.color() {
+            Some(color) => format!("{}{}", count, sym.symbol.color(color)),
+            None => format!("{}{}", count, sym.symbol),
+        },
+        None => String::new(),
+    }
+}
+
+/// Render the bare symbol configured for `name` (no leading count), falling
+/// back to `name` itself when unconfigured so a typo'd key stays visible
+/// instead of silently vanishing.
+pub fn render_symbol(config: &Config, name: &str) -> String {
+    match config.symbol(name) {
+        Some(sym) => match sym.color() {
+            Some(color) => sym.symbol.color(color).to_string(),
+            None => sym.symbol.clone(),
+        },
+        None => name.to_string(),
+    }
+}
+
+/// Walk `format`, replacing every `$name` token found in [`VARIABLES`] with
+/// whatever `resolve` returns for that name, and copying everything else
+/// through unchanged.
+pub fn render_template<F>(format: &str, mut resolve: F) -> String
+where
+    F: FnMut(&str) -> String,
+{
+    let mut result = String::new();
+    let mut chars = format.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+
+        let rest = &format[i + 1..];
+        let matched = VARIABLES
+            .iter()
+            .filter(|v| rest.starts_with(**v))
+            .max_by_key(|v| v.len());
+
+        if let Some(var) = matched {
+            result.push_str(&resolve(var));
+            for _ in 0..var.len() {
+                chars.next();
+            }
+        } else {
+            result.push('$');
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_template_prefers_the_longest_matching_variable() {
+        // "deleted_staged" must win over the shorter "deleted" prefix.
+        let out = render_template("$deleted_staged", |var| var.to_string());
+        assert_eq!(out, "deleted_staged");
+    }
+
+    #[test]
+    fn render_template_passes_through_unknown_tokens_and_literal_text() {
+        let out = render_template("pre-$nope-post", |var| var.to_string());
+        assert_eq!(out, "pre-$nope-post");
+    }
+
+    #[test]
+    fn render_template_resolves_adjacent_variables_independently() {
+        let out = render_template("$ahead$behind", |var| format!("<{}>", var));
+        assert_eq!(out, "<ahead><behind>");
+    }
+
+    #[test]
+    fn parse_color_is_case_insensitive_and_rejects_unknown_names() {
+        assert_eq!(parse_color("Red"), Some(Color::Red));
+        assert_eq!(parse_color("BRIGHT green"), Some(Color::BrightGreen));
+        assert_eq!(parse_color("not-a-color"), None);
+    }
+}
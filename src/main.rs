@@ -1,6 +1,12 @@
+mod cache;
+mod config;
+
 use git2::Error;
 use std::env;
 use colored::*;
+use serde::{Deserialize, Serialize};
+
+use config::Config;
 
 fn main() -> std::io::Result<()> {
     let repo = match git2::Repository::discover(env::current_dir()?.as_path()) {
@@ -8,16 +14,31 @@ fn main() -> std::io::Result<()> {
         Err(_r) => return Ok(()),
     };
 
-    let r = Repository { repository: repo };
+    let mut r = Repository { repository: repo };
+    let cfg = Config::load();
 
-    match r.branch() {
-        Ok(branch) => print!("{}", branch.to_string()),
+    let branch = match r.branch(&cfg) {
+        Ok(branch) => branch,
         Err(e) => panic!("failed to analyze branch: {}", e),
-    }
+    };
 
-    match r.status() {
-        Ok(status) => print!("{}", status.to_string()),
+    let status = match r.status(&cfg) {
+        Ok(status) => status,
         Err(e) => panic!("failed to get status: {}", e),
+    };
+
+    match cfg.output_format {
+        config::OutputFormat::Json => {
+            let view = PromptView::new(&branch, &status);
+            match serde_json::to_string(&view) {
+                Ok(json) => println!("{}", json),
+                Err(e) => panic!("failed to serialize status: {}", e),
+            }
+        }
+        config::OutputFormat::Human => {
+            print!("{}", branch.to_string(&cfg));
+            print!("{}", render_status_line(&cfg, &branch, &status));
+        }
     }
 
     Ok(())
@@ -28,7 +49,113 @@ struct Repository {
 }
 
 impl Repository {
-    fn status(&self) -> Result<RepoStatus, Error> {
+    /// Dispatch to the exact full workdir scan for small repos, or to the
+    /// cached index-vs-HEAD fast path once the index grows past
+    /// `cfg.fast_status_threshold` entries.
+    fn status(&mut self, cfg: &Config) -> Result<RepoStatus, Error> {
+        let index_len = self.repository.index()?.len();
+
+        if index_len <= cfg.fast_status_threshold {
+            return self.status_full();
+        }
+
+        let mut result = self.staged_status_cached()?;
+        self.apply_workdir_status(&mut result)?;
+        result.stashed = self.stash_count()?;
+
+        Ok(result)
+    }
+
+    /// Staged-vs-HEAD half of the fast path, keyed on the index file's
+    /// mtime and HEAD OID via `cache::cache_key` and cached across
+    /// invocations. Only this half is cached: it's the only part that's
+    /// fully determined by the index and HEAD. The workdir-vs-index half
+    /// (`apply_workdir_status`) is recomputed every call instead, since a
+    /// tracked-file edit or a new untracked file changes it without
+    /// touching the index or HEAD, which would otherwise leave the cache
+    /// key unchanged and the prompt showing stale status.
+    fn staged_status_cached(&mut self) -> Result<RepoStatus, Error> {
+        let key = cache::cache_key(&self.repository);
+        if let Some((workdir, key)) = &key {
+            if let Some(cached) = cache::load(workdir, key) {
+                return Ok(cached);
+            }
+        }
+
+        let result = self.staged_status()?;
+        if let Some((workdir, key)) = &key {
+            cache::store(workdir, key, &result);
+        }
+
+        Ok(result)
+    }
+
+    /// Staged changes computed by diffing the index tree against HEAD,
+    /// skipping unchanged directories via their tree hashes.
+    fn staged_status(&mut self) -> Result<RepoStatus, Error> {
+        let head_tree = self.repository.head()?.peel_to_tree()?;
+        let diff = self
+            .repository
+            .diff_tree_to_index(Some(&head_tree), None, None)?;
+
+        let mut result = RepoStatus::default();
+
+        diff.foreach(
+            &mut |delta, _progress| {
+                match delta.status() {
+                    git2::Delta::Added => result.new_files.replace(result.new_files.unwrap_or(0) + 1),
+                    git2::Delta::Modified => result.modifications_staged.replace(result.modifications_staged.unwrap_or(0) + 1),
+                    git2::Delta::Deleted => result.deletions_staged.replace(result.deletions_staged.unwrap_or(0) + 1),
+                    git2::Delta::Renamed => result.renames_staged.replace(result.renames_staged.unwrap_or(0) + 1),
+                    git2::Delta::Conflicted => result.conflicted.replace(result.conflicted.unwrap_or(0) + 1),
+                    _ => None,
+                };
+                true
+            },
+            None,
+            None,
+            None,
+        )?;
+
+        Ok(result)
+    }
+
+    /// Unstaged/untracked workdir changes computed by diffing the workdir
+    /// against the index. Never cached (see `staged_status_cached`).
+    fn apply_workdir_status(&mut self, result: &mut RepoStatus) -> Result<(), Error> {
+        let mut opts = git2::DiffOptions::new();
+        opts.include_untracked(true);
+        let diff = self.repository.diff_index_to_workdir(None, Some(&mut opts))?;
+
+        diff.foreach(
+            &mut |delta, _progress| {
+                match delta.status() {
+                    git2::Delta::Modified => result.modifications.replace(result.modifications.unwrap_or(0) + 1),
+                    git2::Delta::Deleted => result.deletions.replace(result.deletions.unwrap_or(0) + 1),
+                    git2::Delta::Renamed => result.renames.replace(result.renames.unwrap_or(0) + 1),
+                    git2::Delta::Untracked => result.untracked.replace(result.untracked.unwrap_or(0) + 1),
+                    _ => None,
+                };
+                true
+            },
+            None,
+            None,
+            None,
+        )?;
+
+        Ok(())
+    }
+
+    fn stash_count(&mut self) -> Result<Option<usize>, Error> {
+        let mut stashed: Option<usize> = None;
+        self.repository.stash_foreach(|_index, _message, _oid| {
+            stashed.replace(stashed.unwrap_or(0) + 1);
+            true
+        })?;
+        Ok(stashed)
+    }
+
+    fn status_full(&mut self) -> Result<RepoStatus, Error> {
         let mut options = git2::StatusOptions::new();
         options.include_untracked(true);
         options.show(git2::StatusShow::IndexAndWorkdir);
@@ -43,10 +170,13 @@ impl Repository {
             renames: None,
             deletions_staged: None,
             deletions: None,
+            conflicted: None,
+            stashed: None,
         };
 
         for entry in statuses.iter() {
             match entry.status() {
+                s if s.contains(git2::Status::CONFLICTED) => result.conflicted.replace(result.conflicted.unwrap_or(0) + 1),
                 s if s.contains(git2::Status::INDEX_MODIFIED) => result.modifications_staged.replace(result.modifications_staged.unwrap_or(0) + 1),
                 s if s.contains(git2::Status::WT_MODIFIED) => result.modifications.replace(result.modifications.unwrap_or(0) + 1),
                 s if s.contains(git2::Status::INDEX_NEW) => result.new_files.replace(result.new_files.unwrap_or(0) + 1),
@@ -55,45 +185,63 @@ impl Repository {
                 s if s.contains(git2::Status::WT_RENAMED) => result.renames.replace(result.renames.unwrap_or(0) + 1),
                 s if s.contains(git2::Status::INDEX_DELETED) => result.deletions_staged.replace(result.deletions_staged.unwrap_or(0) + 1),
                 s if s.contains(git2::Status::WT_DELETED) => result.deletions.replace(result.deletions.unwrap_or(0) + 1),
-                // s if s.contains(git2::Status::CONFLICTED) => match entry.head_to_index().unwrap().status() {
-
-                // }
                 _ => continue,
             };
         }
 
+        // `statuses` borrows `self.repository` and (having a `Drop` impl)
+        // would otherwise stay borrowed until the end of the function,
+        // conflicting with the mutable borrow `stash_foreach` needs below.
+        drop(statuses);
+
+        let mut stashed: Option<usize> = None;
+        self.repository.stash_foreach(|_index, _message, _oid| {
+            stashed.replace(stashed.unwrap_or(0) + 1);
+            true
+        })?;
+        result.stashed = stashed;
+
         Ok(result)
     }
 
-    fn branch(&self) -> Result<BranchStatus, Error> {
+    fn branch(&self, cfg: &Config) -> Result<BranchStatus, Error> {
         let head = match self.repository.head() {
             Ok(h) => h,
             Err(_e) => return Ok(BranchStatus {
-                name: "detached".to_string(),
+                name: BranchName::Ref("detached".to_string()),
                 local: None,
+                local_base: None,
                 upstream: None,
             }),
         };
-        let mut name = head.name().unwrap();
-        let branch_name = name;
 
-        if name == "refs/heads/master" {
-            name = "🅼"
-        }
+        let name = if self.repository.head_detached().unwrap_or(false) {
+            self.describe_head()
+                .unwrap_or_else(|| BranchName::DetachedOid(head.target().unwrap()))
+        } else {
+            let mut n = head.name().unwrap();
 
-        let local = self
-            .repository
-            .find_branch("master", git2::BranchType::Local)
-            .and_then(|master: git2::Branch| {
-                self.repository
-                    .graph_ahead_behind(head.target().unwrap(), master.get().target().unwrap())
-            })
-            .ok();
+            if n == "refs/heads/master" {
+                n = "🅼"
+            }
 
-        let upstream = self
-            .repository
-            .branch_upstream_name(branch_name)
-            .ok()
+            BranchName::Ref(n.to_string())
+        };
+
+        let local_base = self.resolve_local_base(cfg);
+        let local = local_base.as_ref().and_then(|base| {
+            self.repository
+                .find_branch(base, git2::BranchType::Local)
+                .and_then(|b: git2::Branch| {
+                    self.repository
+                        .graph_ahead_behind(head.target().unwrap(), b.get().target().unwrap())
+                })
+                .ok()
+        });
+
+        let upstream = head
+            .name()
+            .and_then(|branch_name| self.repository.branch_upstream_name(branch_name).ok())
             .and_then(|bname_buf: git2::Buf| {
                 bname_buf.as_str().map(|s| {s.to_string()})
             })
@@ -104,13 +252,57 @@ impl Repository {
             });
 
         Ok(BranchStatus {
-            name: name.to_string(),
+            name,
             local,
+            local_base,
             upstream,
         })
     }
+
+    /// Resolve the local branch that `↔/←/→` is measured against: an
+    /// explicit `cfg.local_base` override if it names a real local branch,
+    /// else the repo's `init.defaultBranch`, else the first of
+    /// `main`/`master`/`trunk` that exists locally.
+    fn resolve_local_base(&self, cfg: &Config) -> Option<String> {
+        if let Some(name) = &cfg.local_base {
+            if self.repository.find_branch(name, git2::BranchType::Local).is_ok() {
+                return Some(name.clone());
+            }
+        }
+
+        if let Some(name) = self
+            .repository
+            .config()
+            .ok()
+            .and_then(|c| c.get_string("init.defaultBranch").ok())
+        {
+            if self.repository.find_branch(&name, git2::BranchType::Local).is_ok() {
+                return Some(name);
+            }
+        }
+
+        ["main", "master", "trunk"]
+            .into_iter()
+            .find(|name| self.repository.find_branch(name, git2::BranchType::Local).is_ok())
+            .map(|name| name.to_string())
+    }
+
+    /// Describe HEAD against tags (e.g. `v1.2.3-4-gabc1234`), falling back
+    /// to the abbreviated commit OID when no tags are reachable.
+    fn describe_head(&self) -> Option<BranchName> {
+        let mut describe_opts = git2::DescribeOptions::new();
+        describe_opts.describe_tags();
+        describe_opts.show_commit_oid_as_fallback(true);
+
+        self.repository
+            .describe(&describe_opts)
+            .and_then(|d| d.format(None))
+            .ok()
+            .map(BranchName::Described)
+    }
 }
 
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 struct RepoStatus {
     modifications_staged: Option<usize>,
     modifications: Option<usize>,
@@ -120,58 +312,247 @@ struct RepoStatus {
     renames: Option<usize>,
     deletions_staged: Option<usize>,
     deletions: Option<usize>,
+    conflicted: Option<usize>,
+    stashed: Option<usize>,
 }
 
 impl RepoStatus {
-    fn to_string(&self) -> String {
-        let mut result = String::new();
+    /// Resolve one of the file-count template variables (`new`, `staged`,
+    /// `modified`, ...). `None` means the variable isn't one of
+    /// `RepoStatus`'s, as opposed to `Some(None)` (no files of that kind),
+    /// which `render_status_line` renders as nothing.
+    fn file_count(&self, var: &str) -> Option<Option<usize>> {
+        match var {
+            "new" => Some(self.new_files),
+            "staged" => Some(self.modifications_staged),
+            "renamed_staged" => Some(self.renames_staged),
+            "deleted_staged" => Some(self.deletions_staged),
+            "modified" => Some(self.modifications),
+            "renamed" => Some(self.renames),
+            "deleted" => Some(self.deletions),
+            "untracked" => Some(self.untracked),
+            "conflicted" => Some(self.conflicted),
+            "stashed" => Some(self.stashed),
+            _ => None,
+        }
+    }
+}
+
+/// Render `cfg.format` once over both `status`'s file counts and
+/// `branch`'s upstream-divergence variables, so a user's template can
+/// interleave and reorder `$ahead`/`$behind`/`$diverged`/`$uptodate`/
+/// `$no_upstream` against `$conflicted`/`$staged`/etc. instead of the
+/// upstream state being permanently pinned ahead of the file counts.
+fn render_status_line(cfg: &Config, branch: &BranchStatus, status: &RepoStatus) -> String {
+    config::render_template(&cfg.format, |var| match status.file_count(var) {
+        Some(Some(n)) => config::render_var(cfg, var, n),
+        Some(None) => String::new(),
+        None => branch.resolve_upstream_var(cfg, var),
+    })
+}
+
+/// Structured view over [`BranchStatus`] and [`RepoStatus`] for `--format
+/// json`/`RGP_FORMAT=json`, so tools that want to consume the prompt
+/// programmatically don't have to parse the ANSI-colored line. Unset
+/// counts are reported as `0` rather than omitted, so consumers can rely
+/// on every field always being present.
+#[derive(Serialize)]
+struct PromptView {
+    branch: String,
+    local_base: Option<String>,
+    local_ahead: usize,
+    local_behind: usize,
+    upstream_ahead: usize,
+    upstream_behind: usize,
+    new: usize,
+    staged: usize,
+    renamed_staged: usize,
+    deleted_staged: usize,
+    modified: usize,
+    renamed: usize,
+    deleted: usize,
+    untracked: usize,
+    conflicted: usize,
+    stashed: usize,
+}
 
-        result.push_str(&self.new_files.map(|i| { format!("{}{}", i, "N".green()) }).unwrap_or("".to_string()));
-        result.push_str(&self.modifications_staged.map(|i| { format!("{}{}", i, "M".green()) }).unwrap_or("".to_string()));
-        result.push_str(&self.renames_staged.map(|i| { format!("{}{}", i, "R".green()) }).unwrap_or("".to_string()));
-        result.push_str(&self.deletions_staged.map(|i| { format!("{}{}", i, "D".green()) }).unwrap_or("".to_string()));
-        result.push_str(&self.modifications.map(|i| { format!("{}{}", i, "M".red()) }).unwrap_or("".to_string()));
-        result.push_str(&self.renames.map(|i| { format!("{}{}", i, "R".red()) }).unwrap_or("".to_string()));
-        result.push_str(&self.deletions.map(|i| { format!("{}{}", i, "D".red()) }).unwrap_or("".to_string()));
-        result.push_str(&self.untracked.map(|i| { format!("{}{}", i, "U".blue()) }).unwrap_or("".to_string()));
+impl PromptView {
+    fn new(branch: &BranchStatus, status: &RepoStatus) -> Self {
+        let (local_ahead, local_behind) = branch.local.unwrap_or((0, 0));
+        let (upstream_ahead, upstream_behind) = branch.upstream.unwrap_or((0, 0));
 
-        result
+        PromptView {
+            branch: branch.name.to_string(),
+            local_base: branch.local_base.clone(),
+            local_ahead,
+            local_behind,
+            upstream_ahead,
+            upstream_behind,
+            new: status.new_files.unwrap_or(0),
+            staged: status.modifications_staged.unwrap_or(0),
+            renamed_staged: status.renames_staged.unwrap_or(0),
+            deleted_staged: status.deletions_staged.unwrap_or(0),
+            modified: status.modifications.unwrap_or(0),
+            renamed: status.renames.unwrap_or(0),
+            deleted: status.deletions.unwrap_or(0),
+            untracked: status.untracked.unwrap_or(0),
+            conflicted: status.conflicted.unwrap_or(0),
+            stashed: status.stashed.unwrap_or(0),
+        }
+    }
+}
+
+/// How the current HEAD is best displayed in the prompt.
+enum BranchName {
+    /// A ref pointing to a named branch (or its glyph substitute, e.g. 🅼).
+    Ref(String),
+    /// A detached HEAD that `git describe` could not name at all.
+    DetachedOid(git2::Oid),
+    /// A detached HEAD rendered via `git describe` (e.g. `v1.2.3-4-gabc1234`).
+    Described(String),
+}
+
+impl BranchName {
+    fn to_string(&self) -> String {
+        match self {
+            BranchName::Ref(name) => name.clone(),
+            BranchName::DetachedOid(oid) => {
+                let hex = oid.to_string();
+                hex[..7.min(hex.len())].to_string()
+            }
+            BranchName::Described(name) => name.clone(),
+        }
     }
 }
 
 struct BranchStatus {
-    name: String,
+    name: BranchName,
     local: Option<(usize, usize)>,
+    /// Name of the local branch `local` was measured against (e.g. `main`),
+    /// so the rendered arrows can be labeled with what they mean.
+    local_base: Option<String>,
     upstream: Option<(usize, usize)>,
 }
 
 impl BranchStatus {
-    fn upstream(&self) -> Option<String> {
-        match self.upstream {
-            Some((a, b)) if a > 0 && b > 0 => Some(format!("{}{}{}", "⇵".yellow(), a, b)),
-            Some((a, 0)) if a > 0 => Some(format!("{}{}", "↓".red(), a)),
-            Some((0, b)) if b > 0 => Some(format!("{}{}", "↑".green(), b)),
-            Some((0, 0)) => Some("≡".to_string()),
-            _ => Some("⚡".red().to_string()),
+    /// Resolve one of the upstream-divergence template variables (`ahead`,
+    /// `behind`, `diverged`, `uptodate`, `no_upstream`) the same way
+    /// `RepoStatus::file_count` resolves file-count variables, so
+    /// `cfg.format` can give them a symbol, color, and position instead of
+    /// the fixed "right after the branch name" slot they used to be stuck in.
+    fn resolve_upstream_var(&self, cfg: &Config, var: &str) -> String {
+        match (var, self.upstream) {
+            ("diverged", Some((a, b))) if a > 0 && b > 0 => format!("{}{}{}", config::render_symbol(cfg, "diverged"), a, b),
+            ("ahead", Some((a, 0))) if a > 0 => format!("{}{}", config::render_symbol(cfg, "ahead"), a),
+            ("behind", Some((0, b))) if b > 0 => format!("{}{}", config::render_symbol(cfg, "behind"), b),
+            ("uptodate", Some((0, 0))) => config::render_symbol(cfg, "uptodate"),
+            ("no_upstream", None) => config::render_symbol(cfg, "no_upstream"),
+            _ => String::new(),
         }
     }
 
-    fn local(&self) -> Option<String> {
+    fn local(&self, cfg: &Config) -> Option<String> {
+        let base = self.local_base.as_deref().unwrap_or("m");
+
         match self.local {
-            Some((a, b)) if a > 0 && b > 0 => Some(format!("m{}{}{}", "↔".magenta(), a, b)),
-            Some((a, 0)) if a > 0 => Some(format!("m{}{}", "←".magenta(), a)),
-            Some((0, b)) if b > 0 => Some(format!("m{}{}", "→".magenta(), b)),
-            _ => Some("⦰".red().to_string()),
+            Some((a, b)) if a > 0 && b > 0 => Some(format!("{}{}{}{}", base, config::render_symbol(cfg, "local_diverged"), a, b)),
+            Some((a, 0)) if a > 0 => Some(format!("{}{}{}", base, config::render_symbol(cfg, "local_ahead"), a)),
+            Some((0, b)) if b > 0 => Some(format!("{}{}{}", base, config::render_symbol(cfg, "local_behind"), b)),
+            _ => Some(config::render_symbol(cfg, "local_none")),
         }
     }
 
-    fn to_string(&self) -> String {
+    fn to_string(&self, cfg: &Config) -> String {
         let mut result = String::new();
 
-        result.push_str(&self.name);
-        result.push_str(&self.local().unwrap_or("".to_string()));
-        result.push_str(&self.upstream().unwrap_or("".to_string()));
+        match cfg.branch_color {
+            Some(color) => result.push_str(&self.name.to_string().color(color).to_string()),
+            None => result.push_str(&self.name.to_string()),
+        }
+        result.push_str(&self.local(cfg).unwrap_or("".to_string()));
 
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_repo(name: &str) -> (Repository, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rgp-main-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // Start on a branch outside the `main`/`master`/`trunk` probe list, so
+        // the first commit doesn't create one of the probed branches itself.
+        let mut opts = git2::RepositoryInitOptions::new();
+        opts.initial_head("work");
+        (Repository { repository: git2::Repository::init_opts(&dir, &opts).unwrap() }, dir)
+    }
+
+    fn commit(repo: &git2::Repository, message: &str) -> git2::Oid {
+        let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+        let tree_id = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .unwrap()
+    }
+
+    fn branch_at_head(repo: &git2::Repository, name: &str) {
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch(name, &head, false).unwrap();
+    }
+
+    #[test]
+    fn resolve_local_base_prefers_the_explicit_override_when_it_exists() {
+        let (r, dir) = temp_repo("override-exists");
+        commit(&r.repository, "first");
+        branch_at_head(&r.repository, "develop");
+        branch_at_head(&r.repository, "main");
+
+        let cfg = Config { local_base: Some("develop".to_string()), ..Config::default() };
+        assert_eq!(r.resolve_local_base(&cfg), Some("develop".to_string()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_local_base_falls_back_to_init_default_branch_when_override_is_missing() {
+        let (r, dir) = temp_repo("override-missing");
+        commit(&r.repository, "first");
+        branch_at_head(&r.repository, "trunk");
+        r.repository.config().unwrap().set_str("init.defaultBranch", "trunk").unwrap();
+
+        let cfg = Config { local_base: Some("nonexistent".to_string()), ..Config::default() };
+        assert_eq!(r.resolve_local_base(&cfg), Some("trunk".to_string()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_local_base_falls_back_to_the_main_master_trunk_probe() {
+        let (r, dir) = temp_repo("probe-order");
+        commit(&r.repository, "first");
+        branch_at_head(&r.repository, "trunk");
+        branch_at_head(&r.repository, "master");
+
+        let cfg = Config::default();
+        assert_eq!(r.resolve_local_base(&cfg), Some("master".to_string()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_local_base_is_none_when_nothing_matches() {
+        let (r, dir) = temp_repo("no-match");
+        commit(&r.repository, "first");
+        branch_at_head(&r.repository, "feature");
+
+        let cfg = Config::default();
+        assert_eq!(r.resolve_local_base(&cfg), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}